@@ -0,0 +1,289 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+
+use rand::rngs::OsRng;
+use rand::Rng;
+
+use crate::config::{Config, Rule};
+
+/// Default high-port range sequences are drawn from, well clear of
+/// well-known and commonly scanned ports.
+const DEFAULT_PORT_RANGE: RangeInclusive<u16> = 20000..=65000;
+/// Default number of ports in a generated sequence.
+const DEFAULT_SEQUENCE_LENGTH: usize = 3;
+/// Random draws attempted per rule before giving up on the requested
+/// constraints, so an unsatisfiable `--port-range`/`--sequence-length`/rule
+/// count combination errors out instead of looping forever.
+const MAX_GENERATION_ATTEMPTS: u32 = 10_000;
+
+/// A single rule specification collected from flags or the interactive
+/// prompt, before a sequence has been synthesized for it.
+#[derive(Clone)]
+pub struct RuleSpec {
+    pub name: String,
+    pub command: String,
+}
+
+/// Options for the `knock init` subcommand, gathered from CLI flags with
+/// any missing pieces filled in interactively.
+pub struct InitOptions {
+    pub output: PathBuf,
+    pub interface: Option<String>,
+    pub timeout: u64,
+    pub sequence_length: usize,
+    pub port_range: RangeInclusive<u16>,
+    pub rules: Vec<RuleSpec>,
+}
+
+impl Default for InitOptions {
+    fn default() -> Self {
+        InitOptions {
+            output: PathBuf::from("config.yaml"),
+            interface: None,
+            timeout: 5,
+            sequence_length: DEFAULT_SEQUENCE_LENGTH,
+            port_range: DEFAULT_PORT_RANGE,
+            rules: Vec::new(),
+        }
+    }
+}
+
+/// Error generating a config, as opposed to the I/O errors `run` otherwise
+/// surfaces.
+#[derive(Debug)]
+pub enum InitError {
+    /// No non-guessable sequence could be drawn within
+    /// `MAX_GENERATION_ATTEMPTS`; the port range is too small for the
+    /// requested sequence length and rule count, or the sequence length is
+    /// below 3, which `is_non_guessable` always rejects.
+    SequenceSpaceExhausted,
+}
+
+impl fmt::Display for InitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InitError::SequenceSpaceExhausted => write!(
+                f,
+                "could not draw a non-guessable sequence from the configured port range; \
+                 widen --port-range or reduce --sequence-length/the number of rules \
+                 (a --sequence-length below 3 can never be drawn)"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for InitError {}
+
+/// Runs the `knock init` wizard: fills in anything missing from `options` by
+/// prompting on stdin, synthesizes a non-guessable port sequence per rule,
+/// and writes the resulting config as YAML to `options.output`.
+pub fn run(mut options: InitOptions) -> io::Result<()> {
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+
+    if options.interface.is_none() {
+        options.interface = Some(prompt(&mut lines, "Network interface", "eth0")?);
+    }
+
+    if options.rules.is_empty() {
+        let rule_count: usize = prompt(&mut lines, "Number of rules", "1")?
+            .parse()
+            .unwrap_or(1);
+
+        for i in 0..rule_count {
+            let name = prompt(&mut lines, "Rule name", &format!("rule-{}", i + 1))?;
+            let command = prompt(&mut lines, "Command to run on match", "")?;
+            options.rules.push(RuleSpec { name, command });
+        }
+    }
+
+    let config = generate_config(&options).map_err(io::Error::other)?;
+    let yaml = serde_yaml::to_string(&config)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    std::fs::write(&options.output, yaml)?;
+
+    println!("Wrote knock config to {}", options.output.display());
+
+    Ok(())
+}
+
+fn prompt(
+    lines: &mut std::io::Lines<std::io::StdinLock<'_>>,
+    label: &str,
+    default: &str,
+) -> io::Result<String> {
+    if default.is_empty() {
+        print!("{}: ", label);
+    } else {
+        print!("{} [{}]: ", label, default);
+    }
+    io::stdout().flush()?;
+
+    let answer = lines.next().transpose()?.unwrap_or_default();
+    let answer = answer.trim();
+
+    Ok(if answer.is_empty() {
+        default.to_string()
+    } else {
+        answer.to_string()
+    })
+}
+
+/// Builds a `Config` with one randomized, non-guessable sequence per rule in
+/// `options`.
+pub fn generate_config(options: &InitOptions) -> Result<Config, InitError> {
+    let mut rng = OsRng;
+    let mut used_ports: HashSet<i32> = HashSet::new();
+    let mut rules = Vec::with_capacity(options.rules.len());
+
+    for spec in &options.rules {
+        let sequence = generate_sequence(
+            &mut rng,
+            options.sequence_length,
+            &options.port_range,
+            &used_ports,
+        )?;
+        used_ports.extend(sequence.iter().copied());
+
+        rules.push(Rule {
+            name: spec.name.clone(),
+            sequence,
+            command: spec.command.clone(),
+            timeout: None,
+            hooks: None,
+        });
+    }
+
+    Ok(Config {
+        interface: options.interface.clone().unwrap_or_default(),
+        timeout: options.timeout,
+        rules,
+        hooks: None,
+    })
+}
+
+/// Draws a random port sequence, rejecting sequences with adjacent or
+/// monotonic runs, repeated ports, or ports already claimed by another rule
+/// — the patterns that make a knock sequence trivially guessable. Gives up
+/// with `InitError::SequenceSpaceExhausted` after `MAX_GENERATION_ATTEMPTS`
+/// rather than looping forever on an unsatisfiable request.
+fn generate_sequence(
+    rng: &mut OsRng,
+    length: usize,
+    port_range: &RangeInclusive<u16>,
+    used_ports: &HashSet<i32>,
+) -> Result<Vec<i32>, InitError> {
+    for _ in 0..MAX_GENERATION_ATTEMPTS {
+        let mut sequence = Vec::with_capacity(length);
+        let mut seen = HashSet::new();
+        let mut valid_draw = true;
+
+        for _ in 0..length {
+            let port = rng.gen_range(*port_range.start()..=*port_range.end()) as i32;
+            if used_ports.contains(&port) || !seen.insert(port) {
+                valid_draw = false;
+                break;
+            }
+            sequence.push(port);
+        }
+
+        if valid_draw && is_non_guessable(&sequence) {
+            return Ok(sequence);
+        }
+    }
+
+    Err(InitError::SequenceSpaceExhausted)
+}
+
+/// A sequence shorter than two ports has no "step" between ports to judge,
+/// so it's trivially guessable by definition rather than vacuously fine.
+fn is_non_guessable(sequence: &[i32]) -> bool {
+    if sequence.len() < 2 {
+        return false;
+    }
+
+    let has_adjacent_step = sequence.windows(2).any(|pair| (pair[0] - pair[1]).abs() == 1);
+    let is_monotonic = sequence.windows(2).all(|pair| pair[0] < pair[1])
+        || sequence.windows(2).all(|pair| pair[0] > pair[1]);
+
+    !has_adjacent_step && !is_monotonic
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_non_guessable_rejects_short_sequences() {
+        assert!(!is_non_guessable(&[]));
+        assert!(!is_non_guessable(&[42]));
+    }
+
+    #[test]
+    fn test_is_non_guessable_rejects_adjacent_steps() {
+        assert!(!is_non_guessable(&[100, 101, 200]));
+    }
+
+    #[test]
+    fn test_is_non_guessable_rejects_monotonic_runs() {
+        assert!(!is_non_guessable(&[100, 200, 300]));
+        assert!(!is_non_guessable(&[300, 200, 100]));
+    }
+
+    #[test]
+    fn test_is_non_guessable_accepts_non_monotonic_non_adjacent() {
+        assert!(is_non_guessable(&[100, 300, 200]));
+    }
+
+    #[test]
+    fn test_generate_sequence_respects_used_ports() {
+        let mut rng = OsRng;
+        let used_ports: HashSet<i32> = (20000..20010).collect();
+        let sequence =
+            generate_sequence(&mut rng, 3, &(20000..=20100), &used_ports).unwrap();
+
+        assert!(sequence.iter().all(|port| !used_ports.contains(port)));
+    }
+
+    #[test]
+    fn test_generate_sequence_errors_when_space_is_exhausted() {
+        let mut rng = OsRng;
+        // a single-port range can never produce a 3-port sequence with no
+        // repeats, so this must error out rather than loop forever
+        let result = generate_sequence(&mut rng, 3, &(20000..=20000), &HashSet::new());
+
+        assert!(matches!(result, Err(InitError::SequenceSpaceExhausted)));
+    }
+
+    #[test]
+    fn test_generate_config_produces_one_sequence_per_rule() {
+        let options = InitOptions {
+            interface: Some("eth0".to_string()),
+            rules: vec![
+                RuleSpec {
+                    name: "enable ssh".to_string(),
+                    command: "ufw allow 22".to_string(),
+                },
+                RuleSpec {
+                    name: "disable ssh".to_string(),
+                    command: "ufw deny 22".to_string(),
+                },
+            ],
+            ..InitOptions::default()
+        };
+
+        let config = generate_config(&options).unwrap();
+        assert_eq!(config.rules.len(), 2);
+
+        let all_ports: Vec<i32> = config
+            .rules
+            .iter()
+            .flat_map(|rule| rule.sequence.clone())
+            .collect();
+        let unique_ports: HashSet<i32> = all_ports.iter().copied().collect();
+        assert_eq!(all_ports.len(), unique_ports.len());
+    }
+}
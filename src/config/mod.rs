@@ -0,0 +1,27 @@
+use serde::{Deserialize, Serialize};
+
+use crate::hooks::Hooks;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Config {
+    pub interface: String,
+    pub timeout: u64,
+    pub rules: Vec<Rule>,
+    #[serde(default)]
+    pub hooks: Option<Hooks>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rule {
+    pub name: String,
+    pub sequence: Vec<i32>,
+    pub command: String,
+    /// Overrides `Config.timeout` for this rule's sliding window.
+    #[serde(default)]
+    pub timeout: Option<u64>,
+    /// Per-rule hook overrides for the progress and match events; any event
+    /// left unset falls back to the hooks configured globally on `Config`.
+    /// Timeout and invalid events always run the global hooks.
+    #[serde(default)]
+    pub hooks: Option<Hooks>,
+}
@@ -0,0 +1,107 @@
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+
+/// A lifecycle event raised by the sequence detector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookEvent {
+    /// A valid port was accepted into an in-progress sequence.
+    Progress,
+    /// A rule's full sequence was matched.
+    Matched,
+    /// An in-progress sequence expired without matching a rule.
+    Timeout,
+    /// A known port broke the prefix of an in-progress rule.
+    Invalid,
+}
+
+impl HookEvent {
+    fn as_str(self) -> &'static str {
+        match self {
+            HookEvent::Progress => "progress",
+            HookEvent::Matched => "matched",
+            HookEvent::Timeout => "timeout",
+            HookEvent::Invalid => "invalid",
+        }
+    }
+}
+
+/// Context passed to a hook command through `KNOCK_*` environment variables.
+pub struct HookContext<'a> {
+    pub client_ip: &'a str,
+    pub rule: Option<&'a str>,
+    pub sequence: &'a [i32],
+}
+
+/// Hook commands to run on the distinct events a knock sequence can raise.
+///
+/// A `Rule` may set its own `Hooks` to override the global ones configured on
+/// `Config` for that rule's progress and match events; any event left unset
+/// falls back to the global hook, if any. A rule's `on_timeout` and
+/// `on_invalid` are not used: those events run the global hooks only.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Hooks {
+    #[serde(default)]
+    pub on_progress: Option<String>,
+    #[serde(default)]
+    pub on_match: Option<String>,
+    #[serde(default)]
+    pub on_timeout: Option<String>,
+    #[serde(default)]
+    pub on_invalid: Option<String>,
+}
+
+impl Hooks {
+    pub(crate) fn command_for(&self, event: HookEvent) -> Option<&str> {
+        match event {
+            HookEvent::Progress => self.on_progress.as_deref(),
+            HookEvent::Matched => self.on_match.as_deref(),
+            HookEvent::Timeout => self.on_timeout.as_deref(),
+            HookEvent::Invalid => self.on_invalid.as_deref(),
+        }
+    }
+
+    /// Runs the configured command for `event`, if any, passing `ctx` through
+    /// `KNOCK_EVENT`, `KNOCK_CLIENT_IP`, `KNOCK_RULE` and `KNOCK_SEQUENCE`.
+    pub fn fire(&self, event: HookEvent, ctx: &HookContext) {
+        let Some(command) = self.command_for(event) else {
+            return;
+        };
+
+        let sequence = ctx
+            .sequence
+            .iter()
+            .map(i32::to_string)
+            .collect::<Vec<_>>()
+            .join(",");
+
+        let result = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .env("KNOCK_EVENT", event.as_str())
+            .env("KNOCK_CLIENT_IP", ctx.client_ip)
+            .env("KNOCK_RULE", ctx.rule.unwrap_or(""))
+            .env("KNOCK_SEQUENCE", sequence)
+            .spawn();
+
+        match result {
+            // wait() on a dedicated thread so the child is reaped instead of
+            // sitting as a zombie until the daemon exits; hooks fire once per
+            // SYN packet on the Progress/Invalid path, so leaving it
+            // unreaped would accumulate one zombie per scanned port.
+            Ok(mut child) => {
+                std::thread::spawn(move || {
+                    let _ = child.wait();
+                });
+            }
+            Err(err) => {
+                println!(
+                    "Failed to run {} hook for {}: {}",
+                    event.as_str(),
+                    ctx.client_ip,
+                    err
+                );
+            }
+        }
+    }
+}
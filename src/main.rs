@@ -0,0 +1,148 @@
+mod commands;
+mod config;
+mod hooks;
+mod metrics;
+mod sequence;
+mod transport;
+
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+
+use commands::init::{InitOptions, RuleSpec};
+
+#[cfg(feature = "ws-transport")]
+use std::sync::{Arc, Mutex};
+
+#[cfg(feature = "ws-transport")]
+use sequence::SequenceDetector;
+
+#[derive(Parser)]
+#[command(name = "knock", about = "A port-knocking daemon")]
+struct Cli {
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Interactively generate a config with randomized, non-guessable knock
+    /// sequences.
+    Init {
+        /// Where to write the generated config.
+        #[arg(long, default_value = "config.yaml")]
+        output: PathBuf,
+        /// Network interface to knock on; prompted for if omitted.
+        #[arg(long)]
+        interface: Option<String>,
+        /// Sequence timeout in seconds.
+        #[arg(long, default_value_t = 5)]
+        timeout: u64,
+        /// Number of ports in each generated sequence.
+        #[arg(long, default_value_t = 3)]
+        sequence_length: usize,
+        /// Inclusive high-port range sequences are drawn from.
+        #[arg(long, default_value = "20000-65000")]
+        port_range: String,
+        /// Rule name and command, e.g. `--rule enable-ssh:"ufw allow 22"`.
+        /// Repeat for multiple rules; prompted for if omitted.
+        #[arg(long = "rule", value_parser = parse_rule_spec)]
+        rules: Vec<RuleSpec>,
+    },
+    /// Run the authenticated WebSocket knock transport for clients that
+    /// can't reach the raw ports a SYN-sequence knock needs.
+    #[cfg(feature = "ws-transport")]
+    Serve {
+        /// Config file to load rules from.
+        #[arg(long, default_value = "config.yaml")]
+        config: PathBuf,
+        /// Address to listen for WebSocket connections on.
+        #[arg(long, default_value = "0.0.0.0:9000")]
+        bind: String,
+        /// Shared secret used to authenticate knock messages.
+        #[arg(long, env = "KNOCK_WS_SECRET")]
+        secret: String,
+        /// Address to serve Prometheus metrics on.
+        #[arg(long, default_value = "0.0.0.0:9090")]
+        metrics_bind: String,
+    },
+}
+
+fn parse_rule_spec(raw: &str) -> Result<RuleSpec, String> {
+    let (name, command) = raw
+        .split_once(':')
+        .ok_or_else(|| format!("expected NAME:COMMAND, got `{}`", raw))?;
+
+    Ok(RuleSpec {
+        name: name.to_string(),
+        command: command.to_string(),
+    })
+}
+
+fn parse_port_range(raw: &str) -> Result<RangeInclusive<u16>, String> {
+    let (start, end) = raw
+        .split_once('-')
+        .ok_or_else(|| format!("expected MIN-MAX, got `{}`", raw))?;
+
+    let start: u16 = start.parse().map_err(|_| format!("invalid port: {}", start))?;
+    let end: u16 = end.parse().map_err(|_| format!("invalid port: {}", end))?;
+
+    if start > end {
+        return Err(format!(
+            "invalid --port-range: start {} is greater than end {}",
+            start, end
+        ));
+    }
+
+    Ok(start..=end)
+}
+
+fn main() -> std::io::Result<()> {
+    env_logger::init();
+    let cli = Cli::parse();
+
+    match cli.command {
+        Commands::Init {
+            output,
+            interface,
+            timeout,
+            sequence_length,
+            port_range,
+            rules,
+        } => {
+            let port_range = parse_port_range(&port_range)
+                .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))?;
+
+            commands::init::run(InitOptions {
+                output,
+                interface,
+                timeout,
+                sequence_length,
+                port_range,
+                rules,
+            })
+        }
+        #[cfg(feature = "ws-transport")]
+        Commands::Serve {
+            config,
+            bind,
+            secret,
+            metrics_bind,
+        } => {
+            let config_contents = std::fs::read_to_string(&config)?;
+            let config: config::Config = serde_yaml::from_str(&config_contents)
+                .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+            let detector: Arc<Mutex<dyn SequenceDetector + Send>> =
+                Arc::new(Mutex::new(sequence::PortSequenceDetector::new(config)));
+
+            std::thread::spawn(move || {
+                if let Err(err) = metrics::serve(&metrics_bind) {
+                    log::error!(event = "metrics_server_failed"; "Metrics endpoint failed: {}", err);
+                }
+            });
+
+            tokio::runtime::Runtime::new()?.block_on(transport::ws::serve(&bind, secret, detector))
+        }
+    }
+}
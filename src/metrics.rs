@@ -0,0 +1,120 @@
+//! Prometheus metrics and structured log events for the sequence detector,
+//! replacing the `println!` calls scattered through `add_sequence` and
+//! `match_sequence`.
+
+use std::sync::OnceLock;
+
+use prometheus::{Encoder, IntCounterVec, IntGauge, Opts, Registry, TextEncoder};
+
+pub struct Metrics {
+    registry: Registry,
+    pub syn_packets_total: IntCounterVec,
+    pub matched_total: IntCounterVec,
+    pub timeout_total: IntCounterVec,
+    pub tracked_clients: IntGauge,
+}
+
+static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+impl Metrics {
+    fn new() -> Metrics {
+        let registry = Registry::new();
+
+        let syn_packets_total = IntCounterVec::new(
+            Opts::new(
+                "knock_syn_packets_total",
+                "SYN packets accepted into an in-progress sequence",
+            ),
+            &["rule", "client_ip"],
+        )
+        .expect("static metric definition is valid");
+        let matched_total = IntCounterVec::new(
+            Opts::new("knock_matched_total", "Knock sequences fully matched"),
+            &["rule"],
+        )
+        .expect("static metric definition is valid");
+        let timeout_total = IntCounterVec::new(
+            Opts::new(
+                "knock_timeout_total",
+                "In-progress sequences that expired without matching a rule",
+            ),
+            &["client_ip"],
+        )
+        .expect("static metric definition is valid");
+        let tracked_clients = IntGauge::new(
+            "knock_tracked_clients",
+            "Clients with a non-empty in-progress sequence right now",
+        )
+        .expect("static metric definition is valid");
+
+        registry
+            .register(Box::new(syn_packets_total.clone()))
+            .expect("metric name is unique");
+        registry
+            .register(Box::new(matched_total.clone()))
+            .expect("metric name is unique");
+        registry
+            .register(Box::new(timeout_total.clone()))
+            .expect("metric name is unique");
+        registry
+            .register(Box::new(tracked_clients.clone()))
+            .expect("metric name is unique");
+
+        Metrics {
+            registry,
+            syn_packets_total,
+            matched_total,
+            timeout_total,
+            tracked_clients,
+        }
+    }
+}
+
+/// The process-wide metrics registry, initialized on first use.
+pub fn metrics() -> &'static Metrics {
+    METRICS.get_or_init(Metrics::new)
+}
+
+/// Groups client IPs into a low-cardinality label: the IPv4 /24 the client
+/// is in, or the raw address for anything else (IPv6, malformed input).
+/// Bucketing keeps `knock_syn_packets_total` from growing one series per
+/// distinct attacker IP during a scan.
+pub fn bucket_client_ip(client_ip: &str) -> String {
+    let octets: Vec<&str> = client_ip.split('.').collect();
+    match octets.as_slice() {
+        [a, b, c, _] => format!("{}.{}.{}.0/24", a, b, c),
+        _ => client_ip.to_string(),
+    }
+}
+
+/// Renders the current metrics in Prometheus text exposition format.
+pub fn encode() -> String {
+    let families = metrics().registry.gather();
+    let mut buffer = Vec::new();
+    TextEncoder::new()
+        .encode(&families, &mut buffer)
+        .expect("encoding registered metrics never fails");
+
+    String::from_utf8(buffer).expect("prometheus text encoding is always valid utf8")
+}
+
+/// Serves `/metrics` in Prometheus text format on `bind_addr`. Blocks the
+/// calling thread, so callers run it on a dedicated thread.
+pub fn serve(bind_addr: &str) -> std::io::Result<()> {
+    let server = tiny_http::Server::http(bind_addr).map_err(std::io::Error::other)?;
+    log::info!(event = "metrics_listening", addr = bind_addr; "Metrics endpoint listening");
+
+    for request in server.incoming_requests() {
+        let body = encode();
+        let response = tiny_http::Response::from_string(body).with_header(
+            tiny_http::Header::from_bytes(
+                &b"Content-Type"[..],
+                &b"text/plain; version=0.0.4"[..],
+            )
+            .expect("static header is valid"),
+        );
+        let _ = request.respond(response);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,17 @@
+mod port_sequence;
+
+pub use port_sequence::PortSequenceDetector;
+
+/// Tracks per-client port-knock progress and matches it against configured
+/// rules.
+pub trait SequenceDetector {
+    /// Records a SYN packet to `sequence` from `client_ip`.
+    fn add_sequence(&mut self, client_ip: String, sequence: i32);
+
+    /// Checks whether `client_ip`'s current sequence matches a rule.
+    fn match_sequence(&mut self, client_ip: &str) -> bool;
+
+    /// Drops clients whose sliding window expired without a new packet
+    /// arriving to discover it.
+    fn evict_expired(&mut self);
+}
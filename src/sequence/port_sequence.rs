@@ -1,31 +1,98 @@
 use std::collections::{HashMap, HashSet};
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::config::Config;
+use crate::config::{Config, Rule};
+use crate::hooks::{HookContext, HookEvent, Hooks};
+use crate::metrics::{bucket_client_ip, metrics};
 
 use crate::sequence::SequenceDetector;
 
+/// Number of broken-prefix attempts, with no match and no idle gap longer
+/// than `FAILURE_COOLDOWN_SECS` in between, before a client's window is
+/// shrunk and it is temporarily locked out.
+const FAILURE_THRESHOLD: u32 = 3;
+/// Floor a client's window can be shrunk to by repeat-offender backoff.
+const MIN_WINDOW_SECS: u64 = 1;
+/// How long a client is locked out (packets silently ignored) once it trips
+/// the failure threshold.
+const LOCKOUT_SECS: u64 = 30;
+/// Idle time after which a client's failure counter decays back to zero.
+const FAILURE_COOLDOWN_SECS: u64 = 60;
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// Per-client knock-sequence progress and repeat-offender tracking, replacing
+/// the old pair of `client_sequences`/`client_timeout` maps.
+#[derive(Debug, Clone)]
+struct ClientState {
+    sequence: Vec<i32>,
+    /// Timestamp of the last valid in-sequence port, refreshed on every
+    /// accepted knock so the window slides instead of expiring from the
+    /// first knock onward.
+    last_seen: u64,
+    /// Consecutive broken-prefix attempts since the last cooldown/decay.
+    failures: u32,
+    /// While set, `add_sequence` ignores this client's packets entirely.
+    locked_until: Option<u64>,
+    /// Window shrunk by backoff; overrides the rule/global timeout when
+    /// smaller.
+    shrunk_window: Option<u64>,
+}
+
+impl ClientState {
+    fn new(now: u64) -> ClientState {
+        ClientState {
+            sequence: Vec::new(),
+            last_seen: now,
+            failures: 0,
+            locked_until: None,
+            shrunk_window: None,
+        }
+    }
+
+    fn is_locked(&self, now: u64) -> bool {
+        self.locked_until.is_some_and(|until| now < until)
+    }
+
+    /// Applies repeat-offender backoff: halves the client's window (down to
+    /// a floor) and locks it out for a cooldown period.
+    fn back_off(&mut self, base_timeout: u64, now: u64) {
+        let current = self.shrunk_window.unwrap_or(base_timeout);
+        self.shrunk_window = Some((current / 2).max(MIN_WINDOW_SECS));
+        self.locked_until = Some(now + LOCKOUT_SECS);
+        self.failures = 0;
+    }
+}
+
 #[derive(Debug)]
 pub struct PortSequenceDetector {
     timeout: u64,
     sequence_set: HashSet<i32>,
     sequence_rules: Vec<Vec<i32>>,
-    client_sequences: HashMap<String, Vec<i32>>,
-    client_timeout: HashMap<String, u64>,
+    rules: Vec<Rule>,
+    hooks: Option<Hooks>,
+    clients: HashMap<String, ClientState>,
 }
 
 impl PortSequenceDetector {
     #[must_use]
     pub fn new(config: Config) -> PortSequenceDetector {
+        let rules = config.rules.clone();
+
         let mut sequence_rules = Vec::new();
-        for rule in config.rules.clone() {
-            sequence_rules.push(rule.sequence);
+        for rule in &rules {
+            sequence_rules.push(rule.sequence.clone());
         }
 
         let mut sequence_set = HashSet::new();
-        for rule in config.rules {
-            for sequence in rule.sequence {
-                sequence_set.insert(sequence);
+        for rule in &rules {
+            for sequence in &rule.sequence {
+                sequence_set.insert(*sequence);
             }
         }
 
@@ -33,8 +100,67 @@ impl PortSequenceDetector {
             timeout: config.timeout,
             sequence_set,
             sequence_rules,
-            client_sequences: HashMap::new(),
-            client_timeout: HashMap::new(),
+            rules,
+            hooks: config.hooks,
+            clients: HashMap::new(),
+        }
+    }
+
+    /// Finds the first configured rule for which `sequence` is a trailing
+    /// prefix, i.e. the client is still on track to complete that rule.
+    fn in_progress_rule(&self, sequence: &[i32]) -> Option<&Rule> {
+        self.rules.iter().find(|rule| {
+            (1..=rule.sequence.len().min(sequence.len()))
+                .any(|len| sequence.ends_with(&rule.sequence[..len]))
+        })
+    }
+
+    /// Returns the hooks that handle `event` for `rule`: the rule's own hooks
+    /// when they set a command for `event`, otherwise the hooks configured
+    /// globally.
+    fn hooks_for<'a>(&'a self, rule: Option<&'a Rule>, event: HookEvent) -> Option<&'a Hooks> {
+        rule.and_then(|rule| rule.hooks.as_ref())
+            .filter(|hooks| hooks.command_for(event).is_some())
+            .or(self.hooks.as_ref())
+    }
+
+    /// The window this client's sequence is allowed to stay alive for,
+    /// taking the in-progress rule's override and backoff shrinkage into
+    /// account.
+    fn effective_timeout(&self, rule: Option<&Rule>, state: &ClientState) -> u64 {
+        let base = rule.and_then(|rule| rule.timeout).unwrap_or(self.timeout);
+        state.shrunk_window.map_or(base, |window| window.min(base))
+    }
+
+    /// Number of clients with a non-empty in-progress sequence. Client
+    /// entries outlive their sequence being cleared (backoff state needs to
+    /// persist across attempts), so this is *not* `self.clients.len()`.
+    fn tracked_client_count(&self) -> i64 {
+        self.clients
+            .values()
+            .filter(|state| !state.sequence.is_empty())
+            .count() as i64
+    }
+
+    fn expire(&mut self, client_ip: &str) {
+        let Some(state) = self.clients.get_mut(client_ip) else {
+            return;
+        };
+
+        log::warn!(event = "timeout", client_ip = client_ip; "Sequence timeout");
+        metrics().timeout_total.with_label_values(&[&bucket_client_ip(client_ip)]).inc();
+        let expired_sequence = std::mem::take(&mut state.sequence);
+        metrics().tracked_clients.set(self.tracked_client_count());
+
+        if let Some(hooks) = &self.hooks {
+            hooks.fire(
+                HookEvent::Timeout,
+                &HookContext {
+                    client_ip,
+                    rule: None,
+                    sequence: &expired_sequence,
+                },
+            );
         }
     }
 }
@@ -46,59 +172,170 @@ impl SequenceDetector for PortSequenceDetector {
             return;
         }
 
-        println!(
-            "SYN packet detected from: {} to target port: {}",
-            client_ip, sequence
-        );
+        let now = now();
+
+        if self
+            .clients
+            .get(&client_ip)
+            .is_some_and(|state| state.is_locked(now))
+        {
+            return;
+        }
 
-        let client_sequence = self
-            .client_sequences
-            .entry(client_ip.clone())
-            .or_insert(Vec::new());
-        client_sequence.push(sequence);
-
-        // get the current time stamp
-        self.client_timeout.entry(client_ip.clone()).or_insert(
-            SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
+        if !self.clients.contains_key(&client_ip) {
+            self.clients.insert(client_ip.clone(), ClientState::new(now));
+        }
+
+        // check for expiry before accepting the new port, so a client that
+        // has been silent past its window starts a fresh sequence
+        let should_expire = {
+            let state = self.clients.get(&client_ip).unwrap();
+            !state.sequence.is_empty()
+                && now - state.last_seen > self.effective_timeout(self.in_progress_rule(&state.sequence), state)
+        };
+        if should_expire {
+            self.expire(&client_ip);
+        }
+
+        let was_in_progress = self
+            .clients
+            .get(&client_ip)
+            .is_some_and(|state| self.in_progress_rule(&state.sequence).is_some());
+
+        let state = self.clients.get_mut(&client_ip).unwrap();
+        if now - state.last_seen > FAILURE_COOLDOWN_SECS {
+            state.failures = 0;
+        }
+        state.sequence.push(sequence);
+        state.last_seen = now;
+
+        let rule_label = self
+            .in_progress_rule(&self.clients[&client_ip].sequence)
+            .map_or("", |rule| rule.name.as_str());
+        log::debug!(
+            event = "progress", client_ip = client_ip.as_str(), rule = rule_label, port = sequence;
+            "SYN packet accepted into sequence"
         );
+        metrics()
+            .syn_packets_total
+            .with_label_values(&[rule_label, &bucket_client_ip(&client_ip)])
+            .inc();
+        metrics().tracked_clients.set(self.tracked_client_count());
+
+        if self.match_sequence(&client_ip) {
+            return;
+        }
 
-        self.match_sequence(&client_ip);
+        let current_sequence = self
+            .clients
+            .get(&client_ip)
+            .map(|state| state.sequence.clone())
+            .unwrap_or_default();
+
+        match self.in_progress_rule(&current_sequence) {
+            Some(rule) => {
+                if let Some(hooks) = self.hooks_for(Some(rule), HookEvent::Progress) {
+                    hooks.fire(
+                        HookEvent::Progress,
+                        &HookContext {
+                            client_ip: &client_ip,
+                            rule: Some(rule.name.as_str()),
+                            sequence: &current_sequence,
+                        },
+                    );
+                }
+            }
+            None if was_in_progress => {
+                log::warn!(
+                    event = "invalid", client_ip = client_ip.as_str();
+                    "Knock broke an in-progress sequence"
+                );
+
+                if let Some(hooks) = &self.hooks {
+                    hooks.fire(
+                        HookEvent::Invalid,
+                        &HookContext {
+                            client_ip: &client_ip,
+                            rule: None,
+                            sequence: &current_sequence,
+                        },
+                    );
+                }
+
+                let base_timeout = self.timeout;
+                if let Some(state) = self.clients.get_mut(&client_ip) {
+                    state.sequence.clear();
+                    state.failures += 1;
+                    if state.failures >= FAILURE_THRESHOLD {
+                        log::warn!(
+                            event = "backoff", client_ip = client_ip.as_str();
+                            "Applying backoff to repeat offender"
+                        );
+                        state.back_off(base_timeout, now);
+                    }
+                }
+                metrics().tracked_clients.set(self.tracked_client_count());
+            }
+            None => {}
+        }
     }
 
     fn match_sequence(&mut self, client_ip: &str) -> bool {
         // Check if the current sequence matches any of the rules
-        let client_sequence = self.client_sequences.get_mut(client_ip);
-        if let Some(sequence) = client_sequence {
-            for rule in &self.sequence_rules {
-                if sequence.ends_with(rule) {
-                    println!("Matched knock sequence: {:?} from: {}", rule, client_ip);
-                    // clear the sequence
-                    sequence.clear();
-                    return true;
-                }
-            }
+        let Some(state) = self.clients.get_mut(client_ip) else {
+            return false;
+        };
 
-            // check if the sequence has expired
-            let timeout_entry = self.client_timeout.get(client_ip);
-            if let Some(timeout) = timeout_entry {
-                let current_time = SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs();
-
-                if current_time - timeout > self.timeout {
-                    println!("Sequence timeout for: {}", client_ip);
-                    sequence.clear();
-                    self.client_timeout.remove(client_ip);
+        for rule in &self.sequence_rules {
+            if state.sequence.ends_with(rule) {
+                // clear the sequence
+                state.sequence.clear();
+                state.failures = 0;
+                state.shrunk_window = None;
+                metrics().tracked_clients.set(self.tracked_client_count());
+
+                let matched_rule = self.rules.iter().find(|r| &r.sequence == rule);
+                let rule_label = matched_rule.map_or("", |r| r.name.as_str());
+                log::info!(
+                    event = "matched", client_ip = client_ip, rule = rule_label;
+                    "Matched knock sequence"
+                );
+                metrics().matched_total.with_label_values(&[rule_label]).inc();
+
+                if let Some(hooks) = self.hooks_for(matched_rule, HookEvent::Matched) {
+                    hooks.fire(
+                        HookEvent::Matched,
+                        &HookContext {
+                            client_ip,
+                            rule: matched_rule.map(|r| r.name.as_str()),
+                            sequence: rule,
+                        },
+                    );
                 }
+
+                return true;
             }
         }
 
         false
     }
+
+    fn evict_expired(&mut self) {
+        let now = now();
+        let expired: Vec<String> = self
+            .clients
+            .iter()
+            .filter(|(_, state)| {
+                !state.sequence.is_empty()
+                    && now - state.last_seen > self.effective_timeout(self.in_progress_rule(&state.sequence), state)
+            })
+            .map(|(client_ip, _)| client_ip.clone())
+            .collect();
+
+        for client_ip in expired {
+            self.expire(&client_ip);
+        }
+    }
 }
 
 #[cfg(test)]
@@ -110,17 +347,22 @@ mod tests {
             interface: "enp3s0".to_string(),
             timeout: 5,
             rules: vec![
-                crate::config::config::Rule {
+                crate::config::Rule {
                     name: "enable ssh".to_string(),
                     sequence: vec![1, 2, 3],
                     command: "ls -lh".to_string(),
+                    timeout: None,
+                    hooks: None,
                 },
-                crate::config::config::Rule {
+                crate::config::Rule {
                     name: "disable ssh".to_string(),
                     sequence: vec![3, 5, 6],
                     command: "du -sh *".to_string(),
+                    timeout: None,
+                    hooks: None,
                 },
             ],
+            hooks: None,
         }
     }
 
@@ -138,7 +380,10 @@ mod tests {
         let config = create_config();
         let mut detector = PortSequenceDetector::new(config);
         detector.add_sequence("127.0.0.1".to_owned(), 3);
-        assert_eq!(detector.client_sequences.get("127.0.0.1"), Some(&vec![3]));
+        assert_eq!(
+            detector.clients.get("127.0.0.1").map(|s| &s.sequence),
+            Some(&vec![3])
+        );
     }
 
     #[test]
@@ -146,7 +391,7 @@ mod tests {
         let config = create_config();
         let mut detector = PortSequenceDetector::new(config);
         detector.add_sequence("127.0.0.1".to_owned(), 9);
-        assert_eq!(detector.client_sequences.get("127.0.0.1"), None);
+        assert!(!detector.clients.contains_key("127.0.0.1"));
     }
 
     #[test]
@@ -157,7 +402,57 @@ mod tests {
         detector.add_sequence("127.0.0.1".to_owned(), 3);
         detector.add_sequence("127.0.0.1".to_owned(), 5);
         detector.add_sequence("127.0.0.1".to_owned(), 6);
-        assert_eq!(detector.match_sequence("127.0.0.1"), false);
-        assert_eq!(detector.client_sequences.get("127.0.0.1").unwrap().len(), 0);
+        assert!(!detector.match_sequence("127.0.0.1"));
+        assert_eq!(
+            detector.clients.get("127.0.0.1").unwrap().sequence.len(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_invalid_sequence_clears_sequence() {
+        let mut config = create_config();
+        config.hooks = Some(crate::hooks::Hooks {
+            on_invalid: Some("true".to_string()),
+            ..Default::default()
+        });
+        let mut detector = PortSequenceDetector::new(config);
+        detector.add_sequence("127.0.0.1".to_owned(), 1);
+        // port 5 is known but does not extend the in-progress "enable ssh" rule
+        detector.add_sequence("127.0.0.1".to_owned(), 5);
+        assert_eq!(
+            detector.clients.get("127.0.0.1").map(|s| &s.sequence),
+            Some(&vec![])
+        );
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_repeat_offender_is_locked_out_after_threshold() {
+        let config = create_config();
+        let mut detector = PortSequenceDetector::new(config);
+
+        // three broken-prefix attempts trip the failure threshold
+        for _ in 0..FAILURE_THRESHOLD {
+            detector.add_sequence("127.0.0.1".to_owned(), 1);
+            detector.add_sequence("127.0.0.1".to_owned(), 5);
+        }
+
+        let state = detector.clients.get("127.0.0.1").unwrap();
+        assert!(state.locked_until.is_some());
+        assert_eq!(state.shrunk_window, Some(2));
+
+        // further knocks are ignored entirely while locked out
+        detector.add_sequence("127.0.0.1".to_owned(), 1);
+        assert_eq!(detector.clients.get("127.0.0.1").unwrap().sequence.len(), 0);
+    }
+
+    #[test]
+    fn test_per_rule_timeout_override() {
+        let mut config = create_config();
+        config.rules[0].timeout = Some(100);
+        let detector = PortSequenceDetector::new(config);
+        let state = ClientState::new(now());
+        let rule = detector.rules.first();
+        assert_eq!(detector.effective_timeout(rule, &state), 100);
+    }
+}
@@ -0,0 +1,6 @@
+//! Knock transports that feed the `SequenceDetector` matching engine.
+//! There is currently no raw packet-sniffing listener; `ws-transport` is
+//! the transport `knock serve` runs, and it's enabled by default.
+
+#[cfg(feature = "ws-transport")]
+pub mod ws;
@@ -0,0 +1,288 @@
+//! Authenticated WebSocket transport: the `knock serve` listener that feeds
+//! `SequenceDetector`. There's no raw packet-sniffing alternative in this
+//! tree, so this is the transport enabled by default.
+//!
+//! Each message carries one knock step plus an HMAC over
+//! `client_ip:sequence:timestamp` so the endpoint can't be driven by anyone
+//! without the shared secret. The real client IP is taken from the
+//! connection's peer address, never from the message body, so a client
+//! can't forge knocks on another IP's behalf. A short-lived replay guard
+//! rejects a signature that's already been accepted, so a captured message
+//! can't be resent to repeatedly feed the detector. Verified steps are
+//! handed to `SequenceDetector::add_sequence`.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use futures_util::StreamExt;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tokio::net::TcpListener;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::sequence::SequenceDetector;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// How far a message's timestamp may drift from the server's clock before
+/// it's rejected as stale (and can't be replayed after that).
+const MAX_CLOCK_SKEW_SECS: u64 = 30;
+
+/// How often the background task sweeps `detector` for clients whose
+/// sliding window expired without a new packet arriving to discover it.
+const EVICTION_INTERVAL_SECS: u64 = 5;
+
+/// One knock step delivered over the WebSocket transport.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KnockMessage {
+    pub sequence: i32,
+    pub timestamp: u64,
+    /// Hex-encoded HMAC-SHA256 over `"{client_ip}:{sequence}:{timestamp}"`
+    /// keyed with the configured shared secret.
+    pub signature: String,
+}
+
+impl KnockMessage {
+    fn signing_payload(&self, client_ip: &str) -> String {
+        format!("{}:{}:{}", client_ip, self.sequence, self.timestamp)
+    }
+
+    /// Verifies the signature against `client_ip`, the address the server
+    /// observed the connection from — never a value taken from the message
+    /// body — so a replayed message can't be accepted from a different
+    /// client IP than the one it was signed for.
+    fn verify(&self, secret: &str, client_ip: &str) -> bool {
+        let Ok(mut mac) = HmacSha256::new_from_slice(secret.as_bytes()) else {
+            return false;
+        };
+        mac.update(self.signing_payload(client_ip).as_bytes());
+        let expected = hex_encode(&mac.finalize().into_bytes());
+
+        constant_time_eq(expected.as_bytes(), self.signature.as_bytes())
+    }
+
+    fn is_fresh(&self, now: u64) -> bool {
+        now.abs_diff(self.timestamp) <= MAX_CLOCK_SKEW_SECS
+    }
+}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Rejects a signature that's already been accepted once, so a captured,
+/// correctly-signed message can't be resent from the same or a different
+/// connection to repeatedly feed the detector. Shared across every
+/// connection, since the whole point of a replay is that it's resent from
+/// somewhere other than where it was first seen.
+struct ReplayGuard {
+    seen: Mutex<HashMap<String, u64>>,
+}
+
+impl ReplayGuard {
+    fn new() -> ReplayGuard {
+        ReplayGuard {
+            seen: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns `true` the first time `signature` is recorded at `now`,
+    /// `false` on every replay. Entries recorded more than twice
+    /// `MAX_CLOCK_SKEW_SECS` ago are pruned on each call: an accepted
+    /// message is dated at most `MAX_CLOCK_SKEW_SECS` ahead of when it was
+    /// recorded, so `is_fresh` would already reject a resend of it by then —
+    /// this keeps the set from growing without bound.
+    fn check_and_record(&self, signature: &str, now: u64) -> bool {
+        let mut seen = self.seen.lock().unwrap();
+        seen.retain(|_, seen_at| now.abs_diff(*seen_at) <= 2 * MAX_CLOCK_SKEW_SECS);
+
+        if seen.contains_key(signature) {
+            return false;
+        }
+
+        seen.insert(signature.to_string(), now);
+        true
+    }
+}
+
+/// Runs the WebSocket knock listener on `bind_addr`, feeding verified steps
+/// into `detector` under the connecting socket's real IP.
+pub async fn serve(
+    bind_addr: &str,
+    shared_secret: String,
+    detector: Arc<Mutex<dyn SequenceDetector + Send>>,
+) -> std::io::Result<()> {
+    let listener = TcpListener::bind(bind_addr).await?;
+    println!("WebSocket knock transport listening on {}", bind_addr);
+
+    tokio::spawn(run_eviction_loop(Arc::clone(&detector)));
+    let replay_guard = Arc::new(ReplayGuard::new());
+
+    loop {
+        let (stream, peer_addr) = listener.accept().await?;
+        let shared_secret = shared_secret.clone();
+        let detector = Arc::clone(&detector);
+        let replay_guard = Arc::clone(&replay_guard);
+
+        tokio::spawn(async move {
+            if let Err(err) =
+                handle_connection(stream, peer_addr, &shared_secret, detector, replay_guard).await
+            {
+                println!("WebSocket knock connection from {} failed: {}", peer_addr, err);
+            }
+        });
+    }
+}
+
+/// Periodically sweeps `detector` for clients whose sliding window expired
+/// without sending another packet, so expiry isn't only ever discovered
+/// when the next SYN/knock for that client happens to arrive.
+async fn run_eviction_loop(detector: Arc<Mutex<dyn SequenceDetector + Send>>) {
+    let mut ticker = tokio::time::interval(std::time::Duration::from_secs(EVICTION_INTERVAL_SECS));
+    loop {
+        ticker.tick().await;
+        detector.lock().unwrap().evict_expired();
+    }
+}
+
+async fn handle_connection(
+    stream: tokio::net::TcpStream,
+    peer_addr: SocketAddr,
+    shared_secret: &str,
+    detector: Arc<Mutex<dyn SequenceDetector + Send>>,
+    replay_guard: Arc<ReplayGuard>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let ws_stream = tokio_tungstenite::accept_async(stream).await?;
+    let (_write, mut read) = ws_stream.split();
+    let client_ip = peer_addr.ip().to_string();
+
+    while let Some(message) = read.next().await {
+        let Message::Text(text) = message? else {
+            continue;
+        };
+
+        let knock: KnockMessage = match serde_json::from_str(&text) {
+            Ok(knock) => knock,
+            Err(err) => {
+                println!("Rejected malformed knock message from {}: {}", client_ip, err);
+                continue;
+            }
+        };
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        if !knock.is_fresh(now) || !knock.verify(shared_secret, &client_ip) {
+            println!("Rejected unauthenticated knock message from {}", client_ip);
+            continue;
+        }
+
+        if !replay_guard.check_and_record(&knock.signature, now) {
+            println!("Rejected replayed knock message from {}", client_ip);
+            continue;
+        }
+
+        detector
+            .lock()
+            .unwrap()
+            .add_sequence(client_ip.clone(), knock.sequence);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sign(sequence: i32, timestamp: u64, client_ip: &str, secret: &str) -> String {
+        let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(format!("{}:{}:{}", client_ip, sequence, timestamp).as_bytes());
+        hex_encode(&mac.finalize().into_bytes())
+    }
+
+    #[test]
+    fn test_verify_accepts_correctly_signed_message() {
+        let secret = "shared-secret";
+        let client_ip = "203.0.113.1";
+        let timestamp = 1_700_000_000;
+        let message = KnockMessage {
+            sequence: 1234,
+            timestamp,
+            signature: sign(1234, timestamp, client_ip, secret),
+        };
+
+        assert!(message.verify(secret, client_ip));
+    }
+
+    #[test]
+    fn test_verify_rejects_wrong_secret() {
+        let client_ip = "203.0.113.1";
+        let timestamp = 1_700_000_000;
+        let message = KnockMessage {
+            sequence: 1234,
+            timestamp,
+            signature: sign(1234, timestamp, client_ip, "shared-secret"),
+        };
+
+        assert!(!message.verify("wrong-secret", client_ip));
+    }
+
+    #[test]
+    fn test_verify_rejects_replay_from_a_different_client_ip() {
+        let secret = "shared-secret";
+        let timestamp = 1_700_000_000;
+        let message = KnockMessage {
+            sequence: 1234,
+            timestamp,
+            signature: sign(1234, timestamp, "203.0.113.1", secret),
+        };
+
+        assert!(!message.verify(secret, "203.0.113.2"));
+    }
+
+    #[test]
+    fn test_is_fresh_rejects_stale_timestamp() {
+        let message = KnockMessage {
+            sequence: 1234,
+            timestamp: 0,
+            signature: String::new(),
+        };
+
+        assert!(!message.is_fresh(MAX_CLOCK_SKEW_SECS + 1));
+    }
+
+    #[test]
+    fn test_replay_guard_accepts_a_signature_once() {
+        let guard = ReplayGuard::new();
+
+        assert!(guard.check_and_record("sig-1", 1_700_000_000));
+        assert!(!guard.check_and_record("sig-1", 1_700_000_001));
+    }
+
+    #[test]
+    fn test_replay_guard_prunes_stale_signatures() {
+        let guard = ReplayGuard::new();
+        let first_seen = 1_700_000_000;
+
+        assert!(guard.check_and_record("sig-1", first_seen));
+        // after it's aged out of the freshness window, even for a message
+        // dated MAX_CLOCK_SKEW_SECS ahead of when it was first seen, is_fresh
+        // would already reject a resend of the original message, so treating
+        // the signature as unseen again is harmless and keeps the set bounded
+        let later = first_seen + 2 * MAX_CLOCK_SKEW_SECS + 1;
+        assert!(guard.check_and_record("sig-1", later));
+    }
+}